@@ -1,9 +1,10 @@
+use std::char;
 use std::str::CharRange;
 
 #[allow(non_camel_case_types)]
 #[deriving(PartialEq, Eq, Show)]
 pub enum Token {
-    LitNum(String, String),
+    LitNum(String, String, String, Option<&'static str>),
     LitStr(String),
     LitStrRaw(String),
     LitByteStr(Vec<u8>),
@@ -11,6 +12,7 @@ pub enum Token {
     LitChar(char),
     LitBool(bool),
     Ident(String),
+    Keyword(Keyword),
     LParen,
     RParen,
     LSqbr,
@@ -43,6 +45,8 @@ pub enum Token {
     FatArrow,
     Octothorpe,
     Dollar,
+    DocComment(String),
+    Invalid(char, Span),
     Eof,
 }
 
@@ -60,9 +64,208 @@ pub enum BinOp {
     ShiftRight,
 }
 
+#[deriving(PartialEq, Eq, Show)]
+pub enum Keyword {
+    Let,
+    Fn,
+    Class,
+    If,
+    Else,
+    For,
+    While,
+    In,
+    Return,
+}
+
+impl Keyword {
+    /// Returns the keyword spelled `s`, if there is one.
+    pub fn from_str(s: &str) -> Option<Keyword> {
+        match s {
+            "let" => Some(Let),
+            "fn" => Some(Fn),
+            "class" => Some(Class),
+            "if" => Some(If),
+            "else" => Some(Else),
+            "for" => Some(For),
+            "while" => Some(While),
+            "in" => Some(In),
+            "return" => Some(Return),
+            _ => None,
+        }
+    }
+
+    /// Returns the source representation of this keyword.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Let => "let",
+            Fn => "fn",
+            Class => "class",
+            If => "if",
+            Else => "else",
+            For => "for",
+            While => "while",
+            In => "in",
+            Return => "return",
+        }
+    }
+}
+
+/// Every infix operator and its left and right binding powers, from loosest to tightest.
+///
+/// Higher numbers bind more tightly. A left binding power lower than the right one makes the
+/// operator left-associative; the assignment operators are right-associative.
+pub static INFIX_OPERATORS: &'static [(&'static str, uint, uint)] = &[
+    ("=", 2, 1), ("+=", 2, 1), ("-=", 2, 1), ("*=", 2, 1), ("/=", 2, 1), ("%=", 2, 1),
+    ("^=", 2, 1), ("&=", 2, 1), ("|=", 2, 1), ("<<=", 2, 1), (">>=", 2, 1),
+    ("||", 3, 4),
+    ("^^", 5, 6),
+    ("&&", 7, 8),
+    ("==", 9, 10), ("<", 9, 10), ("<=", 9, 10), (">", 9, 10), (">=", 9, 10),
+    ("|", 11, 12),
+    ("^", 13, 14),
+    ("&", 15, 16),
+    ("<<", 17, 18), (">>", 17, 18),
+    ("+", 19, 20), ("-", 19, 20),
+    ("*", 21, 22), ("/", 21, 22), ("%", 21, 22),
+];
+
+/// Every prefix operator and its binding power, which is higher than that of any infix operator.
+pub static PREFIX_OPERATORS: &'static [(&'static str, uint)] = &[
+    ("!", 23), ("~", 23), ("-", 23),
+];
+
+impl Token {
+    /// Returns whether this token can be used as a prefix or infix operator.
+    pub fn is_operator(&self) -> bool {
+        self.binding_power().is_some() || self.prefix_binding_power().is_some()
+    }
+
+    /// Returns the left and right binding powers of this token when used as an infix operator,
+    /// as listed in `INFIX_OPERATORS`.
+    pub fn binding_power(&self) -> Option<(uint, uint)> {
+        let sym = match self.operator_symbol() {
+            Some(sym) => sym,
+            None => return None,
+        };
+        INFIX_OPERATORS.iter().find(|&&(s, _, _)| s == sym).map(|&(_, l, r)| (l, r))
+    }
+
+    /// Returns the binding power of this token when used as a prefix operator, as listed in
+    /// `PREFIX_OPERATORS`.
+    pub fn prefix_binding_power(&self) -> Option<uint> {
+        let sym = match self.operator_symbol() {
+            Some(sym) => sym,
+            None => return None,
+        };
+        PREFIX_OPERATORS.iter().find(|&&(s, _)| s == sym).map(|&(_, bp)| bp)
+    }
+
+    // Returns the spelling used for this token in the operator tables, if it could be one.
+    fn operator_symbol(&self) -> Option<&'static str> {
+        match *self {
+            Eq => Some("="),
+            EqEq => Some("=="),
+            Lt => Some("<"),
+            Le => Some("<="),
+            Gt => Some(">"),
+            Ge => Some(">="),
+            AndAnd => Some("&&"),
+            OrOr => Some("||"),
+            XorXor => Some("^^"),
+            Not => Some("!"),
+            Tilde => Some("~"),
+            BinOp(ref op) => Some(op.symbol()),
+            BinOpEq(ref op) => Some(op.assign_symbol()),
+            _ => None,
+        }
+    }
+}
+
+impl BinOp {
+    /// Returns the source representation of this operator, e.g. `<<` for `ShiftLeft`.
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            Plus => "+",
+            Minus => "-",
+            Times => "*",
+            Divide => "/",
+            Modulo => "%",
+            Xor => "^",
+            And => "&",
+            Or => "|",
+            ShiftLeft => "<<",
+            ShiftRight => ">>",
+        }
+    }
+
+    /// Returns the source representation of the compound assignment form of this operator, e.g.
+    /// `<<=` for `ShiftLeft`.
+    pub fn assign_symbol(&self) -> &'static str {
+        match *self {
+            Plus => "+=",
+            Minus => "-=",
+            Times => "*=",
+            Divide => "/=",
+            Modulo => "%=",
+            Xor => "^=",
+            And => "&=",
+            Or => "|=",
+            ShiftLeft => "<<=",
+            ShiftRight => ">>=",
+        }
+    }
+
+    /// Returns the left and right binding powers of this operator in infix position.
+    pub fn binding_power(&self) -> (uint, uint) {
+        BinOp(*self).binding_power().unwrap()
+    }
+}
+
+/// The type suffixes that can follow a numeric literal.
+pub static NUM_SUFFIXES: &'static [&'static str] = &[
+    "i", "i8", "i16", "i32", "i64",
+    "u", "u8", "u16", "u32", "u64",
+    "f", "f32", "f64",
+];
+
+/// The location of a token in its source string.
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub struct Span {
+    /// The byte offset of the start of the token.
+    pub lo: uint,
+    /// The byte offset just past the end of the token.
+    pub hi: uint,
+    /// The line the token starts on, counting from 1.
+    pub line: uint,
+    /// The column (in characters) the token starts at, counting from 1.
+    pub col: uint,
+}
+
+/// A value, usually a token, together with the location it came from.
+#[deriving(PartialEq, Eq, Show)]
+pub struct Spanned<T> {
+    /// The value itself.
+    pub node: T,
+    /// Where in the source string the value was found.
+    pub span: Span,
+}
+
+/// An error encountered while tokenising, such as an unterminated string literal.
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+}
+
 pub struct Tokens<'a> {
     str: &'a str,
     pos: uint,
+    // The start of the token currently being read
+    start: uint,
+    // How far `line` and `col` have been computed up to
+    scanned: uint,
+    line: uint,
+    col: uint,
 }
 
 impl<'a> Tokens<'a> {
@@ -70,7 +273,26 @@ impl<'a> Tokens<'a> {
         Tokens {
             str: str,
             pos: 0,
+            start: 0,
+            scanned: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    // Returns the line and column of `pos`, which must not be before any position this was
+    // previously called with.
+    fn line_col(&mut self, pos: uint) -> (uint, uint) {
+        for c in self.str.slice(self.scanned, pos).chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
         }
+        self.scanned = pos;
+        (self.line, self.col)
     }
 
     fn char_at(&self, pos: uint) -> Option<char> {
@@ -88,11 +310,203 @@ impl<'a> Tokens<'a> {
             Some(self.str.char_range_at(pos))
         }
     }
+
+    fn next_char(&mut self) -> Option<char> {
+        match self.char_range_at(self.pos) {
+            Some(CharRange { ch, next }) => {
+                self.pos = next;
+                Some(ch)
+            }
+            None => None,
+        }
+    }
+
+    // Skips the rest of a block comment after its opening `/*`, including any nested block
+    // comments.
+    fn skip_block_comment(&mut self) -> Result<(), String> {
+        let mut depth = 1u;
+        while depth > 0 {
+            match self.next_char() {
+                Some('/') if self.char_at(self.pos) == Some('*') => {
+                    self.pos += 1;
+                    depth += 1;
+                }
+                Some('*') if self.char_at(self.pos) == Some('/') => {
+                    self.pos += 1;
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => return Err("unterminated block comment".to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the exponent of a numeric literal if one starts at the current position, returning
+    // its sign and digits (e.g. `-4` for `E-4`), or an empty string if there isn't one.
+    fn scan_exponent(&mut self) -> Result<String, String> {
+        match self.char_at(self.pos) {
+            Some('e') | Some('E') => {}
+            _ => return Ok(String::new()),
+        }
+        let mut pos = self.pos + 1;
+        let mut s = String::new();
+        match self.char_at(pos) {
+            Some(c) if c == '+' || c == '-' => {
+                s.push_char(c);
+                pos += 1;
+            }
+            _ => {}
+        }
+        if !self.char_at(pos).map_or(false, |c| c.is_digit()) {
+            self.pos = pos;
+            return Err("expected at least one digit in exponent".to_string())
+        }
+        loop {
+            match self.char_at(pos) {
+                Some(c) if c.is_digit() || c == '_' => {
+                    s.push_char(c);
+                    pos += 1;
+                }
+                _ => break,
+            }
+        }
+        self.pos = pos;
+        Ok(s)
+    }
+
+    // Reads the type suffix (such as `u8` or `f`) directly after a numeric literal, if there is
+    // one. Anything other than one of `NUM_SUFFIXES` is an error.
+    fn scan_num_suffix(&mut self) -> Result<Option<&'static str>, String> {
+        let mut end = self.pos;
+        loop {
+            match self.char_range_at(end) {
+                Some(CharRange { ch, next }) if ch == '_' || ch.is_alphanumeric() => end = next,
+                _ => break,
+            }
+        }
+        let suffix = self.str.slice(self.pos, end);
+        self.pos = end;
+        if suffix.is_empty() {
+            return Ok(None)
+        }
+        match NUM_SUFFIXES.iter().find(|s| **s == suffix) {
+            Some(s) => Ok(Some(*s)),
+            None => Err(format!("invalid suffix `{}` on numeric literal", suffix)),
+        }
+    }
+
+    // Returns the number of `#`s in the opening delimiter of a raw string starting at `pos`, if
+    // there is one there.
+    fn raw_str_hashes(&self, pos: uint) -> Option<uint> {
+        let mut pos = pos;
+        let mut hashes = 0u;
+        while self.char_at(pos) == Some('#') {
+            hashes += 1;
+            pos += 1;
+        }
+        if self.char_at(pos) == Some('"') {
+            Some(hashes)
+        } else {
+            None
+        }
+    }
+
+    // Reads a raw string whose opening delimiter (after the `r`) starts at the current position.
+    // `what` names the kind of literal for error messages.
+    fn scan_raw_str(&mut self, what: &str) -> Result<String, String> {
+        let hashes = self.raw_str_hashes(self.pos).unwrap();
+        self.pos += hashes + 1;
+        let mut s = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') if range(0, hashes).all(|i| self.char_at(self.pos + i) == Some('#')) => {
+                    self.pos += hashes;
+                    return Ok(s)
+                }
+                Some(c) => s.push_char(c),
+                None => return Err(format!("unterminated raw {} literal", what)),
+            }
+        }
+    }
+
+    // Reads the rest of an escape sequence after the backslash. Returns `None` at end of input.
+    // In byte literals, hex escapes may go up to `\xFF` and are returned as the char with that
+    // codepoint.
+    fn scan_escape(&mut self, byte: bool) -> Result<Option<char>, String> {
+        let c = match self.next_char() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        match c {
+            'n' => Ok(Some('\n')),
+            't' => Ok(Some('\t')),
+            'r' => Ok(Some('\r')),
+            '\\' => Ok(Some('\\')),
+            '"' => Ok(Some('"')),
+            '\'' => Ok(Some('\'')),
+            '0' => Ok(Some('\0')),
+            'x' => {
+                let mut n = 0u;
+                for _ in range(0u, 2) {
+                    match self.next_char() {
+                        Some(c) if c.is_digit_radix(16) => n = n * 16 + c.to_digit(16).unwrap(),
+                        Some(c) =>
+                            return Err(format!("invalid character in hex escape: `{}`", c)),
+                        None => return Ok(None),
+                    }
+                }
+                if n > 0x7F && !byte {
+                    return Err(format!("hex escape out of range: `\\x{:X}` (must be at most \
+                                        `\\x7F`)", n));
+                }
+                Ok(Some(n as u8 as char))
+            }
+            'u' if !byte => {
+                match self.next_char() {
+                    Some('{') => {}
+                    Some(c) => return Err(format!("expected an opening brace after `\\u`, \
+                                                   found `{}`", c)),
+                    None => return Ok(None),
+                }
+                let mut n = 0u32;
+                let mut digits = 0u;
+                loop {
+                    match self.next_char() {
+                        Some('}') => break,
+                        Some(c) if c.is_digit_radix(16) => {
+                            digits += 1;
+                            if digits > 6 {
+                                return Err("unicode escape must have at most 6 hex digits"
+                                           .to_string());
+                            }
+                            n = n * 16 + c.to_digit(16).unwrap() as u32;
+                        }
+                        Some(c) => return Err(format!("invalid character in unicode escape: \
+                                                       `{}`", c)),
+                        None => return Ok(None),
+                    }
+                }
+                if digits == 0 {
+                    return Err("empty unicode escape".to_string());
+                }
+                match char::from_u32(n) {
+                    Some(c) => Ok(Some(c)),
+                    None => Err(format!("invalid unicode escape: {:X} is not a unicode scalar \
+                                         value", n)),
+                }
+            }
+            'u' => Err("unicode escapes are not allowed in byte string literals".to_string()),
+            c => Err(format!("unknown character escape: `\\{}`", c)),
+        }
+    }
 }
 
-impl<'a> Iterator<Token> for Tokens<'a> {
-    fn next(&mut self) -> Option<Token> {
+impl<'a> Tokens<'a> {
+    // Reads the next token, recording where it starts in `self.start`.
+    fn next_token(&mut self) -> Result<Option<Token>, String> {
         while self.pos < self.str.len() {
+            self.start = self.pos;
             let CharRange { ch, next: pos } = self.str.char_range_at(self.pos);
             self.pos = pos;
             if ch.is_whitespace() { continue; }
@@ -101,24 +515,24 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                 None => ('\0', self.str.len()),
             };
             match (ch, next) {
-                ('(', _) => return Some(LParen),
-                (')', _) => return Some(RParen),
-                ('[', _) => return Some(LSqbr),
-                (']', _) => return Some(RSqbr),
-                ('{', _) => return Some(LBrace),
-                ('}', _) => return Some(RBrace),
+                ('(', _) => return Ok(Some(LParen)),
+                (')', _) => return Ok(Some(RParen)),
+                ('[', _) => return Ok(Some(LSqbr)),
+                (']', _) => return Ok(Some(RSqbr)),
+                ('{', _) => return Ok(Some(LBrace)),
+                ('}', _) => return Ok(Some(RBrace)),
                 ('=', '=') => {
                     self.pos = nextpos;
-                    return Some(EqEq)
+                    return Ok(Some(EqEq))
                 }
                 ('=', '>') => {
                     self.pos = nextpos;
-                    return Some(FatArrow)
+                    return Ok(Some(FatArrow))
                 }
-                ('=', _) => return Some(Eq),
+                ('=', _) => return Ok(Some(Eq)),
                 ('>', '=') => {
                     self.pos = nextpos;
-                    return Some(Ge)
+                    return Ok(Some(Ge))
                 }
                 ('>', '>') => {
                     self.pos = nextpos;
@@ -129,15 +543,15 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                     match next {
                         '=' => {
                             self.pos = nextpos;
-                            return Some(BinOpEq(ShiftRight))
+                            return Ok(Some(BinOpEq(ShiftRight)))
                         }
-                        _ => return Some(BinOp(ShiftRight)),
+                        _ => return Ok(Some(BinOp(ShiftRight))),
                     }
                 }
-                ('>', _) => return Some(Gt),
+                ('>', _) => return Ok(Some(Gt)),
                 ('<', '=') => {
                     self.pos = nextpos;
-                    return Some(Le)
+                    return Ok(Some(Le))
                 }
                 ('<', '<') => {
                     self.pos = nextpos;
@@ -148,75 +562,105 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                     match next {
                         '=' => {
                             self.pos = nextpos;
-                            return Some(BinOpEq(ShiftLeft))
+                            return Ok(Some(BinOpEq(ShiftLeft)))
                         }
-                        _ => return Some(BinOp(ShiftLeft)),
+                        _ => return Ok(Some(BinOp(ShiftLeft))),
                     }
                 }
                 ('<', '-') => {
                     self.pos = nextpos;
-                    return Some(LArrow)
+                    return Ok(Some(LArrow))
                 }
-                ('<', _) => return Some(Lt),
+                ('<', _) => return Ok(Some(Lt)),
                 ('&', '&') => {
                     self.pos = nextpos;
-                    return Some(AndAnd)
+                    return Ok(Some(AndAnd))
                 }
                 ('&', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(And))
+                    return Ok(Some(BinOpEq(And)))
                 }
-                ('&', _) => return Some(BinOp(And)),
+                ('&', _) => return Ok(Some(BinOp(And))),
                 ('|', '|') => {
                     self.pos = nextpos;
-                    return Some(OrOr)
+                    return Ok(Some(OrOr))
                 }
                 ('|', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Or))
+                    return Ok(Some(BinOpEq(Or)))
                 }
-                ('|', _) => return Some(BinOp(Or)),
+                ('|', _) => return Ok(Some(BinOp(Or))),
                 ('^', '^') => {
                     self.pos = nextpos;
-                    return Some(XorXor)
+                    return Ok(Some(XorXor))
                 }
                 ('^', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Xor))
+                    return Ok(Some(BinOpEq(Xor)))
                 }
-                ('^', _) => return Some(BinOp(Xor)),
-                ('!', _) => return Some(Not),
-                ('~', _) => return Some(Tilde),
+                ('^', _) => return Ok(Some(BinOp(Xor))),
+                ('!', _) => return Ok(Some(Not)),
+                ('~', _) => return Ok(Some(Tilde)),
                 ('+', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Plus))
+                    return Ok(Some(BinOpEq(Plus)))
                 }
-                ('+', _) => return Some(BinOp(Plus)),
+                ('+', _) => return Ok(Some(BinOp(Plus))),
                 ('-', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Minus))
+                    return Ok(Some(BinOpEq(Minus)))
                 }
                 ('-', '>') => {
                     self.pos = nextpos;
-                    return Some(RArrow)
+                    return Ok(Some(RArrow))
                 }
-                ('-', _) => return Some(BinOp(Minus)),
+                ('-', _) => return Ok(Some(BinOp(Minus))),
                 ('*', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Times))
+                    return Ok(Some(BinOpEq(Times)))
+                }
+                ('*', _) => return Ok(Some(BinOp(Times))),
+                // Line comment, or doc comment if it starts with exactly three slashes
+                ('/', '/') => {
+                    let end = match self.str.slice_from(nextpos).find('\n') {
+                        Some(i) => nextpos + i,
+                        None => self.str.len(),
+                    };
+                    self.pos = end;
+                    if self.char_at(nextpos) == Some('/')
+                       && self.char_at(nextpos + 1) != Some('/') {
+                        let mut doc = self.str.slice(nextpos + 1, end);
+                        if doc.ends_with("\r") {
+                            doc = doc.slice_to(doc.len() - 1);
+                        }
+                        return Ok(Some(DocComment(doc.to_string())))
+                    }
+                    continue
+                }
+                // Block comment, or doc comment if it starts with exactly two asterisks
+                ('/', '*') => {
+                    self.pos = nextpos;
+                    let doc = self.char_at(self.pos) == Some('*')
+                           && self.char_at(self.pos + 1) != Some('*')
+                           && self.char_at(self.pos + 1) != Some('/');
+                    try!(self.skip_block_comment());
+                    if doc {
+                        let doc = self.str.slice(nextpos + 1, self.pos - 2).to_string();
+                        return Ok(Some(DocComment(doc)))
+                    }
+                    continue
                 }
-                ('*', _) => return Some(BinOp(Times)),
                 ('/', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Divide))
+                    return Ok(Some(BinOpEq(Divide)))
                 }
-                ('/', _) => return Some(BinOp(Divide)),
+                ('/', _) => return Ok(Some(BinOp(Divide))),
                 ('%', '=') => {
                     self.pos = nextpos;
-                    return Some(BinOpEq(Modulo))
+                    return Ok(Some(BinOpEq(Modulo)))
                 }
-                ('%', _) => return Some(BinOp(Modulo)),
-                ('@', _) => return Some(At),
+                ('%', _) => return Ok(Some(BinOp(Modulo))),
+                ('@', _) => return Ok(Some(At)),
                 ('.', '.') => {
                     self.pos = nextpos;
                     let (next, nextpos) = match self.char_range_at(self.pos) {
@@ -226,30 +670,60 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                     match next {
                         '.' => {
                             self.pos = nextpos;
-                            return Some(DotDotDot)
+                            return Ok(Some(DotDotDot))
                         }
-                        _ => return Some(DotDot),
+                        _ => return Ok(Some(DotDot)),
                     }
                 }
-                ('.', _) => return Some(Dot),
-                (',', _) => return Some(Comma),
-                (';', _) => return Some(Semicolon),
+                ('.', _) => return Ok(Some(Dot)),
+                (',', _) => return Ok(Some(Comma)),
+                (';', _) => return Ok(Some(Semicolon)),
                 (':', ':') => {
                     self.pos = nextpos;
-                    return Some(T_PAAMAYIM_NEKUDOTAYIM)
+                    return Ok(Some(T_PAAMAYIM_NEKUDOTAYIM))
+                }
+                (':', _) => return Ok(Some(Colon)),
+                ('#', _) => return Ok(Some(Octothorpe)),
+                ('$', _) => return Ok(Some(Dollar)),
+                // Byte string literal
+                ('b', '"') => {
+                    self.pos = nextpos;
+                    let mut v = Vec::new();
+                    loop {
+                        match self.next_char() {
+                            Some('"') => break,
+                            Some('\\') => match try!(self.scan_escape(true)) {
+                                Some(c) => v.push(c as u8),
+                                None => return Err("unterminated byte string literal".to_string()),
+                            },
+                            Some(c) if (c as u32) < 0x80 => v.push(c as u8),
+                            Some(c) => return Err(format!("non-ASCII character in byte string \
+                                                           literal: `{}`", c)),
+                            None => return Err("unterminated byte string literal".to_string()),
+                        }
+                    }
+                    return Ok(Some(LitByteStr(v)))
+                }
+                // Raw byte string literal
+                ('b', 'r') if self.raw_str_hashes(nextpos).is_some() => {
+                    self.pos = nextpos;
+                    let s = try!(self.scan_raw_str("byte string"));
+                    match s.as_slice().chars().find(|&c| (c as u32) >= 0x80) {
+                        Some(c) => return Err(format!("non-ASCII character in raw byte string \
+                                                       literal: `{}`", c)),
+                        None => {}
+                    }
+                    return Ok(Some(LitByteStrRaw(s.into_bytes())))
+                }
+                // Raw string literal
+                ('r', '"') | ('r', '#') if self.raw_str_hashes(self.pos).is_some() => {
+                    return Ok(Some(LitStrRaw(try!(self.scan_raw_str("string")))))
                 }
-                (':', _) => return Some(Colon),
-                ('#', _) => return Some(Octothorpe),
-                ('$', _) => return Some(Dollar),
                 // Identifier
                 (mut c, _) if c == '_' || c.is_alphabetic() => {
                     let mut s = format!("{}", c);
                     c = self.char_range_at(self.pos).map(|x| x.ch).unwrap_or('\0');
-                    let mut i = 0;
                     while c == '_' || c.is_alphanumeric() {
-                        println!("{}", c);
-                        i += 1;
-                        if i == 10 { fail!() }
                         s.push_char(c);
                         self.pos = nextpos;
                         match self.char_range_at(self.pos) {
@@ -261,60 +735,49 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                         }
                     }
                     match s {
-                        ref s if s.as_slice() == "true" => return Some(LitBool(true)),
-                        ref s if s.as_slice() == "false" => return Some(LitBool(false)),
-                        s => return Some(Ident(s)),
+                        ref s if s.as_slice() == "true" => return Ok(Some(LitBool(true))),
+                        ref s if s.as_slice() == "false" => return Ok(Some(LitBool(false))),
+                        _ => {}
+                    }
+                    let kw = Keyword::from_str(s.as_slice());
+                    match kw {
+                        Some(kw) => return Ok(Some(Keyword(kw))),
+                        None => return Ok(Some(Ident(s))),
                     }
                 }
                 // Char literal
-                // TODO: escapes
                 ('\'', _)  => {
-                    let mut c;
-                    match self.char_range_at(self.pos) {
-                        Some(CharRange { ch, next }) => {
-                            nextpos = next;
-                            c = ch;
-                        }
-                        None => fail!("unterminated char literal"),
-                    }
-                    self.pos = nextpos;
-                    match self.char_range_at(self.pos) {
-                        Some(CharRange { ch: '\'', next }) => {
-                            nextpos = next;
-                        }
-                        Some(CharRange { ch: c, .. }) =>
-                            fail!("expected `'`, found `{}`", c),
-                        _ => fail!("unterminated char literal"),
+                    let c = match self.next_char() {
+                        Some('\\') => match try!(self.scan_escape(false)) {
+                            Some(c) => c,
+                            None => return Err("unterminated char literal".to_string()),
+                        },
+                        Some('\'') => return Err("empty char literal".to_string()),
+                        Some(c) => c,
+                        None => return Err("unterminated char literal".to_string()),
+                    };
+                    match self.next_char() {
+                        Some('\'') => {}
+                        Some(c) => return Err(format!("expected `'`, found `{}`", c)),
+                        None => return Err("unterminated char literal".to_string()),
                     }
-                    self.pos = nextpos;
-                    return Some(LitChar(c))
+                    return Ok(Some(LitChar(c)))
                 }
                 // String literal
-                // TODO: escapes, raw, byte
                 ('"', _) => {
                     let mut s = String::new();
-                    while self.char_at(nextpos) != Some('\"') {
-                        let c;
-                        match self.char_range_at(self.pos) {
-                            Some(CharRange { ch, next }) => {
-                                nextpos = next;
-                                c = ch;
-                            }
-                            None => fail!("unterminated string literal"),
-                        }
-                        s.push_char(c);
-                        self.pos = nextpos;
-                    }
-                    match self.char_range_at(self.pos) {
-                        Some(CharRange { ch: '"', next }) => {
-                            nextpos = next;
+                    loop {
+                        match self.next_char() {
+                            Some('"') => break,
+                            Some('\\') => match try!(self.scan_escape(false)) {
+                                Some(c) => s.push_char(c),
+                                None => return Err("unterminated string literal".to_string()),
+                            },
+                            Some(c) => s.push_char(c),
+                            None => return Err("unterminated string literal".to_string()),
                         }
-                        Some(CharRange { ch: c, .. }) =>
-                            fail!("expected `\"`, found `{}`", c),
-                        _ => fail!("unterminated string literal"),
                     }
-                    self.pos = nextpos;
-                    return Some(LitStr(s))
+                    return Ok(Some(LitStr(s)))
                 }
                 // Parse number
                 // TODO: `.3`
@@ -330,39 +793,77 @@ impl<'a> Iterator<Token> for Tokens<'a> {
                             }
                             None => break,
                         }
-                        println!("s1 “{}” + ‘{}’", s1, c);
                         s1.push_char(c);
                         self.pos = nextpos;
                     }
-                    if !(self.char_at(self.pos) == Some('.')) {
-                        return Some(LitNum(s1, String::new()))
-                    }
-                    self.pos += 1;
+                    // Only treat a `.` as starting the fraction if a digit follows it, so that
+                    // `0..n` and `1.max(2)` lex as a number followed by `..` or `.`
                     let mut s2 = String::new();
-                    while self.char_at(self.pos).unwrap_or('\0').is_digit()
-                       || self.char_at(self.pos) == Some('_')
-                       || self.char_at(self.pos) == Some('.') {
-                        let mut c: char;
-                        match self.char_range_at(self.pos) {
-                            Some(CharRange { ch, next }) => {
-                                nextpos = next;
-                                c = ch;
+                    if self.char_at(self.pos) == Some('.')
+                       && self.char_at(self.pos + 1).map_or(false, |c| c.is_digit()) {
+                        self.pos += 1;
+                        while self.char_at(self.pos).unwrap_or('\0').is_digit()
+                           || self.char_at(self.pos) == Some('_') {
+                            let mut c: char;
+                            match self.char_range_at(self.pos) {
+                                Some(CharRange { ch, next }) => {
+                                    nextpos = next;
+                                    c = ch;
+                                }
+                                None => break,
                             }
-                            None => break,
+                            s2.push_char(c);
+                            self.pos = nextpos;
                         }
-                        println!("s2 “{}” + ‘{}’", s2, c);
-                        s2.push_char(c);
-                        self.pos = nextpos;
                     }
-                    return Some(LitNum(s1, s2))
+                    let s3 = try!(self.scan_exponent());
+                    let suffix = try!(self.scan_num_suffix());
+                    match suffix {
+                        Some(suffix) if !suffix.starts_with("f")
+                                     && !(s2.is_empty() && s3.is_empty()) =>
+                            return Err(format!("integer suffix `{}` on a float literal", suffix)),
+                        _ => {}
+                    }
+                    return Ok(Some(LitNum(s1, s2, s3, suffix)))
+                }
+                // Anything else is reported as an invalid token, and lexing carries on after it
+                (c, _) => {
+                    let (line, col) = self.line_col(self.start);
+                    let span = Span { lo: self.start, hi: self.pos, line: line, col: col };
+                    return Ok(Some(Invalid(c, span)))
                 }
-                _ => unimplemented!(),
             }
         }
-        None
+        Ok(None)
     }
 }
 
+impl<'a> Iterator<Result<Spanned<Token>, LexError>> for Tokens<'a> {
+    fn next(&mut self) -> Option<Result<Spanned<Token>, LexError>> {
+        let result = self.next_token();
+        let (line, col) = self.line_col(self.start);
+        let span = Span { lo: self.start, hi: self.pos, line: line, col: col };
+        match result {
+            Ok(Some(tok)) => Some(Ok(Spanned { node: tok, span: span })),
+            Ok(None) => None,
+            Err(message) => {
+                // There's no sensible way to carry on after an error, so stop here.
+                self.pos = self.str.len();
+                Some(Err(LexError { message: message, span: span }))
+            }
+        }
+    }
+}
+
+/// Tokenises the whole of `str`, stopping at the first error.
+pub fn tokenise(str: &str) -> Result<Vec<Spanned<Token>>, LexError> {
+    let mut toks = Vec::new();
+    for tok in Tokens::from_str(str) {
+        toks.push(try!(tok));
+    }
+    Ok(toks)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -371,14 +872,51 @@ mod tests {
         ($i:ident: $e:expr => $($f:expr),*) => {
             #[test]
             fn $i() {
-                let toks: Vec<Token> = Tokens::from_str($e).collect();
+                let toks: Vec<Token> = Tokens::from_str($e).map(|t| t.unwrap().node).collect();
                 assert_eq!(toks, vec![$($f),*]);
             }
         }
     }
+
+    macro_rules! error_test {
+        ($i:ident: $e:expr => $msg:expr) => {
+            #[test]
+            fn $i() {
+                match tokenise($e) {
+                    Ok(toks) => fail!("expected an error, found {}", toks),
+                    Err(e) => assert_eq!(e.message.as_slice(), $msg),
+                }
+            }
+        }
+    }
     
     token_test!(brackets: "(\r[{  \t} ] \n)" => LParen, LSqbr, LBrace, RBrace, RSqbr, RParen)
 
+    token_test!(line_comment:
+        "a // b c\n/ // d /* e\n//\n/=// f" =>
+            Ident("a".to_string()), BinOp(Divide), BinOpEq(Divide)
+    )
+
+    token_test!(block_comment:
+        "a /* b /* c */ d // */ e /**/ f /***/ g /*/ */ h */ * /" =>
+            Ident("a".to_string()), Ident("e".to_string()), Ident("f".to_string()),
+            Ident("g".to_string()), Ident("h".to_string()), BinOp(Times), BinOp(Divide),
+            BinOp(Times), BinOp(Divide)
+    )
+
+    error_test!(unterminated_block_comment: "a /* /* */" => "unterminated block comment")
+
+    token_test!(doc_comment:
+        "/// outer\n//// not doc\n/** block */ /**/ /*** no */ a //! x\n///" =>
+            DocComment(" outer".to_string()), DocComment(" block ".to_string()),
+            Ident("a".to_string()), DocComment("".to_string())
+    )
+
+    token_test!(doc_comment_crlf:
+        "/// one\r\n///\r\na" =>
+            DocComment(" one".to_string()), DocComment("".to_string()), Ident("a".to_string())
+    )
+
     token_test!(cmp:
         "= = ==< << == = => ==<== == >= > >>" =>
             Eq, Eq, EqEq, Lt, BinOp(ShiftLeft), EqEq, Eq, FatArrow, EqEq, Le, Eq, EqEq, Ge, Gt, BinOp(ShiftRight)
@@ -405,6 +943,21 @@ mod tests {
             Ident("_".to_string())
     )
 
+    token_test!(keywords:
+        "let fn class if else for while in return lets _fn In" =>
+            Keyword(Let), Keyword(Fn), Keyword(Class), Keyword(If), Keyword(Else), Keyword(For),
+            Keyword(While), Keyword(In), Keyword(Return), Ident("lets".to_string()),
+            Ident("_fn".to_string()), Ident("In".to_string())
+    )
+
+    #[test]
+    fn keyword_as_str() {
+        let kws = [Let, Fn, Class, If, Else, For, While, In, Return];
+        for kw in kws.iter() {
+            assert_eq!(Keyword::from_str(kw.as_str()), Some(*kw));
+        }
+    }
+
     token_test!(char:
         "/'h'$ 'e'" =>
             BinOp(Divide), LitChar('h'), Dollar, LitChar('e')
@@ -415,9 +968,298 @@ mod tests {
             LitStr("hello".to_string()), Dollar, LitStr("wórld".to_string()), Tilde
     )
 
+    token_test!(string_escapes:
+        r#" "a\n\t\r\\\"\0\x41" "" "b" "# =>
+            LitStr("a\n\t\r\\\"\0A".to_string()), LitStr("".to_string()), LitStr("b".to_string())
+    )
+
+    token_test!(char_escapes:
+        r"'\n' '\'' '\\' '\x7f'" =>
+            LitChar('\n'), LitChar('\''), LitChar('\\'), LitChar('\x7f')
+    )
+
+    error_test!(unknown_escape: r#""\q""# => "unknown character escape: `\\q`")
+
+    error_test!(hex_escape_out_of_range:
+        r#""\x80""# =>
+            "hex escape out of range: `\\x80` (must be at most `\\x7F`)"
+    )
+
+    error_test!(bad_hex_escape: r"'\x4g'" => "invalid character in hex escape: `g`")
+
+    token_test!(unicode_escapes:
+        r#" "\u{1F4A9}x\u{e9}" '\u{0}' '\u{10FFFF}' "# =>
+            LitStr("\U0001F4A9x\u00e9".to_string()), LitChar('\0'), LitChar('\U0010FFFF')
+    )
+
+    error_test!(unicode_escape_surrogate:
+        r"'\u{D800}'" =>
+            "invalid unicode escape: D800 is not a unicode scalar value"
+    )
+
+    error_test!(unicode_escape_too_large:
+        r#""\u{110000}""# =>
+            "invalid unicode escape: 110000 is not a unicode scalar value"
+    )
+
+    error_test!(unicode_escape_too_long:
+        r#""\u{0000041}""# =>
+            "unicode escape must have at most 6 hex digits"
+    )
+
+    error_test!(unicode_escape_unbraced:
+        r#""\u0041""# =>
+            "expected an opening brace after `\\u`, found `0`"
+    )
+
+    token_test!(raw_string:
+        r####"r"a\n" r#"say "hi""# r##"a"#b"## r"" r x r#x"#### =>
+            LitStrRaw("a\\n".to_string()), LitStrRaw("say \"hi\"".to_string()),
+            LitStrRaw("a\"#b".to_string()), LitStrRaw("".to_string()), Ident("r".to_string()),
+            Ident("x".to_string()), Ident("r".to_string()), Octothorpe, Ident("x".to_string())
+    )
+
+    error_test!(unterminated_raw_string: r##"r#"abc""## => "unterminated raw string literal")
+
+    token_test!(byte_string:
+        r#"b"hi\n\xff\x00\"" b"" b x"# =>
+            LitByteStr(vec![104, 105, 10, 255, 0, 34]), LitByteStr(Vec::new()),
+            Ident("b".to_string()), Ident("x".to_string())
+    )
+
+    error_test!(byte_string_non_ascii:
+        r#"b"café""# =>
+            "non-ASCII character in byte string literal: `é`"
+    )
+
+    error_test!(byte_string_unicode_escape:
+        r#"b"\u{41}""# =>
+            "unicode escapes are not allowed in byte string literals"
+    )
+
+    token_test!(raw_byte_string:
+        r###"br"a\n" br#"say "hi""# br"" br x"### =>
+            LitByteStrRaw(vec![97, 92, 110]), LitByteStrRaw(b"say \"hi\"".to_vec()),
+            LitByteStrRaw(Vec::new()), Ident("br".to_string()), Ident("x".to_string())
+    )
+
+    error_test!(raw_byte_string_non_ascii:
+        r##"br#"café"#"## =>
+            "non-ASCII character in raw byte string literal: `é`"
+    )
+
+    error_test!(unterminated_raw_byte_string: r#"br"abc"# => "unterminated raw byte string literal")
+
+    fn num(int: &str, frac: &str, exp: &str, suffix: Option<&'static str>) -> Token {
+        LitNum(int.to_string(), frac.to_string(), exp.to_string(), suffix)
+    }
+
     token_test!(num:
         "5 1. 3.4" =>
-            LitNum("5".to_string(), "".to_string()), LitNum("1".to_string(), "".to_string()),
-            LitNum("3".to_string(), "4".to_string())
+            num("5", "", "", None), num("1", "", "", None), Dot, num("3", "4", "", None)
+    )
+
+    token_test!(num_range:
+        "0..n 0..10 0.5..1.5" =>
+            num("0", "", "", None), DotDot, Ident("n".to_string()),
+            num("0", "", "", None), DotDot, num("10", "", "", None),
+            num("0", "5", "", None), DotDot, num("1", "5", "", None)
+    )
+
+    token_test!(num_method:
+        "1.max(2) t.0.len()" =>
+            num("1", "", "", None), Dot, Ident("max".to_string()), LParen,
+            num("2", "", "", None), RParen, Ident("t".to_string()), Dot,
+            num("0", "", "", None), Dot, Ident("len".to_string()), LParen, RParen
+    )
+
+    token_test!(num_exponent:
+        "1.5e10 3E-4 2e+1_0" =>
+            num("1", "5", "10", None), num("3", "", "-4", None), num("2", "", "+1_0", None)
+    )
+
+    token_test!(num_dot_e:
+        "1.exp() x.0.e 2.5e1" =>
+            num("1", "", "", None), Dot, Ident("exp".to_string()), LParen, RParen,
+            Ident("x".to_string()), Dot, num("0", "", "", None), Dot, Ident("e".to_string()),
+            num("2", "5", "1", None)
+    )
+
+    error_test!(exponent_without_digits: "7e" => "expected at least one digit in exponent")
+
+    error_test!(signed_exponent_without_digits:
+        "8E+ 1" =>
+            "expected at least one digit in exponent"
+    )
+
+    token_test!(num_suffix:
+        "10i 2.5f 255u8 1e3f64 1_u" =>
+            num("10", "", "", Some("i")), num("2", "5", "", Some("f")),
+            num("255", "", "", Some("u8")), num("1", "", "3", Some("f64")),
+            num("1_", "", "", Some("u"))
+    )
+
+    error_test!(invalid_suffix: "255u9" => "invalid suffix `u9` on numeric literal")
+
+    error_test!(invalid_int_suffix: "7i128" => "invalid suffix `i128` on numeric literal")
+
+    error_test!(invalid_float_suffix: "2.5u9" => "invalid suffix `u9` on numeric literal")
+
+    error_test!(hex_like_suffix: "0x" => "invalid suffix `x` on numeric literal")
+
+    error_test!(int_suffix_on_float: "2.5u8" => "integer suffix `u8` on a float literal")
+
+    #[test]
+    fn spans() {
+        let spans: Vec<Span> = Tokens::from_str("let x\n  = \"é\"; // c\n/** d */")
+                                   .map(|t| t.unwrap().span).collect();
+        assert_eq!(spans, vec![
+            Span { lo: 0, hi: 3, line: 1, col: 1 },
+            Span { lo: 4, hi: 5, line: 1, col: 5 },
+            Span { lo: 8, hi: 9, line: 2, col: 3 },
+            Span { lo: 10, hi: 14, line: 2, col: 5 },
+            Span { lo: 14, hi: 15, line: 2, col: 8 },
+            Span { lo: 21, hi: 29, line: 3, col: 1 },
+        ]);
+    }
+
+    token_test!(invalid:
+        "a ?? é\u00b7 b" =>
+            Ident("a".to_string()),
+            Invalid('?', Span { lo: 2, hi: 3, line: 1, col: 3 }),
+            Invalid('?', Span { lo: 3, hi: 4, line: 1, col: 4 }),
+            Ident("é".to_string()),
+            Invalid('\u00b7', Span { lo: 7, hi: 9, line: 1, col: 7 }),
+            Ident("b".to_string())
     )
-}
\ No newline at end of file
+
+    #[test]
+    fn error_span() {
+        let mut toks = Tokens::from_str("a\n  \"bc");
+        assert!(toks.next().unwrap().is_ok());
+        assert_eq!(toks.next(), Some(Err(LexError {
+            message: "unterminated string literal".to_string(),
+            span: Span { lo: 4, hi: 7, line: 2, col: 3 },
+        })));
+        assert_eq!(toks.next(), None);
+    }
+
+    #[test]
+    fn binop_symbol() {
+        let toks: Vec<Token> = Tokens::from_str("+ - * / % ^ & | << >>")
+                                   .map(|t| t.unwrap().node).collect();
+        let ops = [Plus, Minus, Times, Divide, Modulo, Xor, And, Or, ShiftLeft, ShiftRight];
+        for (tok, op) in toks.iter().zip(ops.iter()) {
+            assert_eq!(*tok, BinOp(*op));
+            assert_eq!(tokenise(op.symbol()).unwrap().move_iter().map(|t| t.node)
+                                            .collect::<Vec<Token>>(),
+                       vec![BinOp(*op)]);
+        }
+    }
+
+    #[test]
+    fn binding_power() {
+        use std::cmp;
+
+        // Each group binds more tightly than the one before it, and all operators in a group
+        // bind equally tightly.
+        let groups = vec![
+            vec![Eq, BinOpEq(Plus), BinOpEq(ShiftRight)],
+            vec![OrOr],
+            vec![XorXor],
+            vec![AndAnd],
+            vec![EqEq, Lt, Le, Gt, Ge],
+            vec![BinOp(Or)],
+            vec![BinOp(Xor)],
+            vec![BinOp(And)],
+            vec![BinOp(ShiftLeft), BinOp(ShiftRight)],
+            vec![BinOp(Plus), BinOp(Minus)],
+            vec![BinOp(Times), BinOp(Divide), BinOp(Modulo)],
+        ];
+        let mut loosest = 0u;
+        for group in groups.iter() {
+            let (l, r) = group.get(0).binding_power().unwrap();
+            for tok in group.iter() {
+                assert_eq!(tok.binding_power(), Some((l, r)));
+            }
+            assert!(cmp::min(l, r) > loosest);
+            loosest = cmp::max(l, r);
+        }
+        for tok in [Not, Tilde, BinOp(Minus)].iter() {
+            assert!(tok.prefix_binding_power().unwrap() > loosest);
+        }
+
+        let (l, r) = BinOp(Minus).binding_power().unwrap();
+        assert!(l < r);
+        let (l, r) = BinOpEq(Plus).binding_power().unwrap();
+        assert!(l > r);
+        assert_eq!(Comma.binding_power(), None);
+        assert_eq!(Not.binding_power(), None);
+    }
+
+    #[test]
+    fn operator_tables() {
+        for &(sym, l, r) in INFIX_OPERATORS.iter() {
+            let toks = tokenise(sym).unwrap();
+            assert_eq!(toks.len(), 1);
+            assert_eq!(toks.get(0).node.binding_power(), Some((l, r)));
+        }
+        for &(sym, bp) in PREFIX_OPERATORS.iter() {
+            let toks = tokenise(sym).unwrap();
+            assert_eq!(toks.len(), 1);
+            assert_eq!(toks.get(0).node.prefix_binding_power(), Some(bp));
+        }
+    }
+
+    #[test]
+    fn is_operator() {
+        assert!(BinOp(Modulo).is_operator());
+        assert!(Not.is_operator());
+        assert!(Eq.is_operator());
+        assert!(!LParen.is_operator());
+        assert!(!Ident("a".to_string()).is_operator());
+    }
+
+    // Runs every `tests/lexer/*.feo` file through the tokeniser and compares the result against
+    // the `.tokens` file next to it. Set `FEO_UPDATE_GOLDEN` to regenerate the expected output.
+    #[test]
+    fn corpus() {
+        use std::io::File;
+        use std::io::fs;
+        use std::os;
+
+        let update = os::getenv("FEO_UPDATE_GOLDEN").is_some();
+        let mut paths = fs::readdir(&Path::new("tests/lexer")).unwrap();
+        paths.sort_by(|a, b| a.as_vec().cmp(&b.as_vec()));
+        let mut failures = Vec::new();
+        for path in paths.iter().filter(|p| p.extension_str() == Some("feo")) {
+            let src = File::open(path).read_to_string().unwrap();
+            let actual = dump_tokens(src.as_slice());
+            let golden = path.with_extension("tokens");
+            if update {
+                File::create(&golden).write_str(actual.as_slice()).unwrap();
+                continue;
+            }
+            let expected = File::open(&golden).read_to_string().unwrap();
+            if actual != expected {
+                failures.push(format!("{}\n--- expected\n{}--- actual\n{}",
+                                      path.display(), expected, actual));
+            }
+        }
+        if !failures.is_empty() {
+            fail!("lexer corpus mismatch:\n{}", failures.connect("\n"));
+        }
+    }
+
+    fn dump_tokens(src: &str) -> String {
+        let mut out = String::new();
+        for tok in Tokens::from_str(src) {
+            match tok {
+                Ok(tok) => out.push_str(format!("{}\n", tok.node).as_slice()),
+                Err(e) => out.push_str(format!("error: {}:{}: {}\n", e.span.line, e.span.col,
+                                               e.message).as_slice()),
+            }
+        }
+        out
+    }
+}